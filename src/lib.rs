@@ -1,4 +1,9 @@
-use std::io::{stdout, Write};
+use std::array;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, stdout, Write};
+use std::iter;
+use std::mem;
+use std::slice;
 
 pub const DOCUMENTATION: &str = "grep searches a file for a given pattern.  Execute by
 grep [flags] regular_expression file_list
@@ -20,6 +25,8 @@ lower-case are always ignored.  Blank lines never match.  The expression
 should be quoted to prevent file-name translation.
 x      An ordinary character (not mentioned below) matches that character.
 '\'    The backslash quotes any character.  "\$" matches a dollar-sign.
+'\nnn' A backslash followed by one to three octal digits matches the
+       byte with that value.  "\101" matches "A".
 '^'    A circumflex at the beginning of an expression matches the
        beginning of a line.
 '$'    A dollar-sign at the end of an expression matches the end of a line.
@@ -27,7 +34,8 @@ x      An ordinary character (not mentioned below) matches that character.
 ':a'   A colon matches a class of characters described by the following
 ':d'     character.  ":a" matches any alphabetic, ":d" matches digits,
 ':n'     ":n" matches alphanumerics, ": " matches spaces, tabs, and
-': '     other control characters, such as new-line.
+': '     other control characters, such as new-line.  ":p" matches
+':p'     ASCII punctuation, which ": " does not.
 '*'    An expression followed by an asterisk matches zero or more
        occurrances of that expression: "fo*" matches "f", "fo"
        "foo", etc.
@@ -43,16 +51,206 @@ x      An ordinary character (not mentioned below) matches that character.
        matches "abc" but not "axb".  A range of characters may be
        specified by two characters separated by "-".  Note that,
        [a-z] matches alphabetics, while [z-a] never matches.
-The concatenation of regular expressions is a regular expression."#;
+The concatenation of regular expressions is a regular expression.
+With extended syntax:
+'|'    A vertical bar separates alternatives.  "cat|dog" matches
+       "cat" or "dog".
+'()'   Parentheses group an expression, so "(ab)+" matches "abab".
+'{}'   An expression followed by "{n}", "{n,}", or "{n,m}" matches
+       n to m occurrances of that expression.  Like '-', the optional
+       occurrances are never given back: "a{1,2}a" does not match "aa".
+'++'   An expression followed by two plus signs matches one or more
+       occurrances of that expression and never gives any back.
+With verbose syntax:
+'#'    A number sign starts a comment to the end of the line.
+       Unescaped whitespace outside of brackets is ignored.  "a b"
+       matches "ab".  The space in ": " is not ignored."#;
+
+/// Help for a command-line flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlagHelp {
+    pub flag: char,
+    pub help: &'static str,
+}
+
+/// Help for an element of the pattern syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyntaxHelp {
+    pub syntax: &'static str,
+    pub help: &'static str,
+}
+
+/// Returns help for each flag, as in `DOCUMENTATION`, for front-ends that
+/// render help themselves.
+pub fn flag_help() -> &'static [FlagHelp] {
+    &[
+        FlagHelp {
+            flag: 'c',
+            help: "Only a count of matching lines is printed",
+        },
+        FlagHelp {
+            flag: 'f',
+            help: "Print file name for matching lines switch, see below",
+        },
+        FlagHelp {
+            flag: 'n',
+            help: "Each line is preceeded by its line number",
+        },
+        FlagHelp {
+            flag: 'v',
+            help: "Only print non-matching lines",
+        },
+    ]
+}
+
+/// Returns help for each element of the pattern syntax, as in `PATDOC`, for
+/// front-ends that render help themselves.
+pub fn pattern_syntax_help() -> &'static [SyntaxHelp] {
+    &[
+        SyntaxHelp {
+            syntax: "x",
+            help: "An ordinary character matches that character",
+        },
+        SyntaxHelp {
+            syntax: "\\",
+            help: "The backslash quotes any character",
+        },
+        SyntaxHelp {
+            syntax: "\\nnn",
+            help: "One to three octal digits match the byte with that value",
+        },
+        SyntaxHelp {
+            syntax: "^",
+            help: "Matches the beginning of a line",
+        },
+        SyntaxHelp {
+            syntax: "$",
+            help: "Matches the end of a line",
+        },
+        SyntaxHelp {
+            syntax: ".",
+            help: "Matches any character except new-line",
+        },
+        SyntaxHelp {
+            syntax: ":a",
+            help: "Matches any alphabetic",
+        },
+        SyntaxHelp {
+            syntax: ":d",
+            help: "Matches digits",
+        },
+        SyntaxHelp {
+            syntax: ":n",
+            help: "Matches alphanumerics",
+        },
+        SyntaxHelp {
+            syntax: ": ",
+            help: "Matches spaces, tabs, and other control characters",
+        },
+        SyntaxHelp {
+            syntax: ":p",
+            help: "Matches ASCII punctuation",
+        },
+        SyntaxHelp {
+            syntax: "*",
+            help: "Matches zero or more occurrances of the preceding expression",
+        },
+        SyntaxHelp {
+            syntax: "+",
+            help: "Matches one or more occurrances of the preceding expression",
+        },
+        SyntaxHelp {
+            syntax: "-",
+            help: "Optionally matches the preceding expression",
+        },
+        SyntaxHelp {
+            syntax: "[]",
+            help: "Matches any character in the brackets, or with a leading \
+                   circumflex, any character except new-line and those. \
+                   Two characters separated by - give a range",
+        },
+        SyntaxHelp {
+            syntax: "|",
+            help: "In extended mode, separates alternatives",
+        },
+        SyntaxHelp {
+            syntax: "()",
+            help: "In extended mode, groups an expression",
+        },
+        SyntaxHelp {
+            syntax: "{}",
+            help: "In extended mode, {n}, {n,}, or {n,m} matches n to m \
+                   occurrances of the preceding expression",
+        },
+        SyntaxHelp {
+            syntax: "++",
+            help: "In extended mode, matches one or more occurrances of the \
+                   preceding expression without giving any back",
+        },
+        SyntaxHelp {
+            syntax: "#",
+            help: "In verbose mode, starts a comment, and unescaped whitespace \
+                   is ignored",
+        },
+    ]
+}
 
 const PMAX: usize = 256;
 
 #[derive(Clone, Debug)]
 pub struct Compiler {
     debug: u32,
+    options: CompileOptions,
     pbuf: Vec<u8>,
 }
 
+/// Options controlling pattern compilation. The defaults match grep.c.
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    /// The maximum length of a source pattern in bytes. Longer patterns are
+    /// rejected before any compilation is done. Unlimited by default.
+    pub max_source_len: Option<usize>,
+    /// Whether literals and classes are lowercased, so that they match
+    /// case-insensitively against lowercased lines. On by default. When off,
+    /// they are stored as written and lines should be matched as-is.
+    pub case_insensitive: bool,
+    /// Whether class members are lowercased when `case_insensitive` is on.
+    /// On by default. When off, classes match exactly the bytes written, so
+    /// `[A-Z]` matches only uppercase letters.
+    pub fold_classes: bool,
+    /// Whether to enable syntax beyond grep.c, as in egrep. Off by default.
+    /// When on, `|` separates alternatives, `(` and `)` group patterns, and
+    /// `{n}`, `{n,}`, or `{n,m}` repeats the preceding pattern from `n` to `m`
+    /// times. The `m - n` optional copies are `-` repetitions, which never
+    /// give back what they matched, so `a{1,2}a` does not match `aa`.
+    /// Alternatives and groups cannot be empty. A `++` repetition is
+    /// possessive, so it never gives back what it matched.
+    pub extended: bool,
+    /// Whether to ignore whitespace and comments, for laying out long
+    /// patterns readably. Off by default. When on, unescaped whitespace outside
+    /// of classes is skipped and `#` starts a comment to the end of the line.
+    /// The space in the `: ` class is part of it, so is not skipped.
+    pub verbose: bool,
+    /// Whether to reject patterns whose backtracking could take superlinear
+    /// time, for running untrusted patterns. Off by default. When on, a
+    /// pattern must have a [`Complexity::Linear`] complexity class. The check
+    /// is conservative, so `a*a*` is rejected, though it matches like `a*`.
+    pub linear_only: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            max_source_len: None,
+            case_insensitive: true,
+            fold_classes: true,
+            extended: false,
+            verbose: false,
+            linear_only: false,
+        }
+    }
+}
+
 /// Literal character (case-insensitive)
 const CHAR: u8 = 1;
 /// `^` Beginning of line
@@ -83,6 +281,12 @@ const PUNCT: u8 = 13;
 const RANGE: u8 = 14;
 /// End of the pattern or a repetition
 const ENDPAT: u8 = 15;
+/// `:p` or `:P`, i.e., `[!-/:-@[-`{-~]`
+const APUNCT: u8 = 16;
+/// `|` Alternation of the patterns ending at the next two `ENDPAT`s
+const ALT: u8 = 17;
+/// `++` One or more repetitions, which never gives back what it matched
+const PPLUS: u8 = 18;
 
 #[derive(Clone, Debug)]
 pub struct Error {
@@ -92,19 +296,97 @@ pub struct Error {
 
 #[derive(Clone, Debug)]
 pub enum ErrorKind {
-    BadPat { source: Box<[u8]>, offset: usize },
+    /// An error in the pattern. `offset` is one past the offending byte, like
+    /// the `stop` pointer in grep.c, so the offending byte is at `offset - 1`.
+    BadPat {
+        source: Box<[u8]>,
+        offset: usize,
+    },
+    SourceTooLong {
+        len: usize,
+        max: usize,
+    },
+    /// The pattern could backtrack superlinearly and
+    /// [`CompileOptions::linear_only`] is on.
+    NotLinear(Complexity),
     Other,
 }
 
+impl Error {
+    /// Returns the 1-based line and column of the offending byte in a bad
+    /// pattern, for placing diagnostics in an editor. Columns count bytes, so
+    /// each byte of a tab or escape is one column.
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        let ErrorKind::BadPat { source, offset } = &self.kind else {
+            return None;
+        };
+        let i = offset.saturating_sub(1).min(source.len());
+        let before = &source[..i];
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |j| j + 1);
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        Some((line, i - line_start + 1))
+    }
+}
+
+impl Display for Error {
+    /// Formats the error like grep.c, naming the byte compilation stopped at.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::BadPat { source, offset } => {
+                writeln!(
+                    f,
+                    "-GREP-E-{}, pattern is\"{}\"",
+                    self.msg,
+                    String::from_utf8_lossy(source),
+                )?;
+                write!(f, "-GREP-E-Stopped at byte {offset}")?;
+                if let Some(c) = offset.checked_sub(1).and_then(|i| source.get(i)) {
+                    write!(f, ", '{}'", c.escape_ascii())?;
+                }
+                Ok(())
+            }
+            ErrorKind::SourceTooLong { len, max } => {
+                write!(f, "{}: {len} bytes exceeds the maximum of {max}", self.msg)
+            }
+            ErrorKind::NotLinear(complexity) => {
+                write!(f, "{}: complexity is {complexity:?}", self.msg)
+            }
+            ErrorKind::Other => f.write_str(self.msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl Compiler {
     pub fn new(debug: u32) -> Self {
+        Compiler::with_options(debug, CompileOptions::default())
+    }
+
+    pub fn with_options(debug: u32, options: CompileOptions) -> Self {
         Compiler {
             debug,
+            options,
             pbuf: Vec::with_capacity(PMAX),
         }
     }
 
     pub fn compile(&mut self, source: &[u8]) -> Result<(), Error> {
+        if let Some(max) = self.options.max_source_len {
+            if source.len() > max {
+                return Err(Error {
+                    msg: "Pattern too long",
+                    kind: ErrorKind::SourceTooLong {
+                        len: source.len(),
+                        max,
+                    },
+                });
+            }
+        }
+
         if self.debug != 0 {
             let mut stdout = stdout().lock();
             stdout.write_all(b"Pattern = \"").unwrap();
@@ -113,19 +395,51 @@ impl Compiler {
         }
 
         let mut pat_start = 0;
+        // Whether the last pattern can be repeated, which is not the case for
+        // an anchor, a repetition, or the start of an alternative. The opcode
+        // at pat_start cannot tell this, since a group may start with any.
+        let mut repeatable = false;
+        // Whether the last token was a `+` repetition, which a second `+`
+        // makes possessive.
+        let mut after_plus = false;
+        // The start of the current alternative and the number of alternations
+        // whose right alternatives are still open.
+        let mut branch_start = self.pbuf.len();
+        let mut alts = 0;
+        // The start of the innermost group and the saved state of the groups
+        // enclosing it, with the offset of each `(` for errors.
+        let mut group_start = self.pbuf.len();
+        let mut groups = Vec::new();
         let mut i = 0;
         while i < source.len() {
             let c = source[i];
             i += 1;
 
+            if self.options.verbose {
+                if c.is_ascii_whitespace() {
+                    continue;
+                } else if c == b'#' {
+                    i = source[i..]
+                        .iter()
+                        .position(|&c| c == b'\n')
+                        .map_or(source.len(), |j| i + j + 1);
+                    continue;
+                }
+            }
+
+            // A `+` directly after a PLUS makes it possessive.
+            if mem::take(&mut after_plus) && c == b'+' && self.options.extended {
+                self.pbuf[pat_start] = PPLUS;
+                continue;
+            }
+
             // STAR, PLUS, and MINUS are special.
             if c == b'*' || c == b'+' || c == b'-' {
-                if matches!(
-                    self.pbuf.last(),
-                    None | Some(&(BOL | EOL | STAR | PLUS | MINUS))
-                ) {
+                if !repeatable {
                     return Err(badpat("Illegal occurrance op.", source, i));
                 }
+                repeatable = false;
+                after_plus = c == b'+';
                 let pat_end = self.pbuf.len();
                 self.store(ENDPAT)?; // Placeholder
                 self.store(ENDPAT)?;
@@ -140,8 +454,86 @@ impl Compiler {
                 continue;
             }
 
+            if c == b'{' && self.options.extended {
+                if !repeatable {
+                    return Err(badpat("Illegal occurrance op.", source, i));
+                }
+                let (min, max);
+                (min, max, i) = bounds(source, i)?;
+                // Expand the last pattern into min copies, followed by either
+                // a STAR copy or max - min MINUS copies.
+                let pat = self.pbuf.split_off(pat_start);
+                if pat.is_empty() {
+                    return Err(badpat("Illegal occurrance op.", source, i));
+                }
+                for _ in 0..min {
+                    self.store_all(&pat)?;
+                }
+                match max {
+                    None => self.store_repeat(STAR, &pat)?,
+                    Some(max) => {
+                        for _ in min..max {
+                            self.store_repeat(MINUS, &pat)?;
+                        }
+                    }
+                }
+                // `{0}` removes the pattern, which leaves nothing to repeat.
+                repeatable = self.pbuf.len() != pat_start;
+                continue;
+            }
+
+            if c == b'|' && self.options.extended {
+                if self.pbuf.len() == branch_start {
+                    return Err(badpat("Empty alternative", source, i));
+                }
+                repeatable = false;
+                let branch_end = self.pbuf.len();
+                self.store(ENDPAT)?; // Placeholder
+                self.store(ENDPAT)?;
+                // Shift the alternative so far up by one
+                self.pbuf
+                    .copy_within(branch_start..branch_end, branch_start + 1);
+                // and make it the left of an alternation.
+                self.pbuf[branch_start] = ALT;
+                branch_start = self.pbuf.len();
+                alts += 1;
+                continue;
+            }
+
+            if c == b'(' && self.options.extended {
+                groups.push((branch_start, alts, group_start, i));
+                repeatable = false;
+                branch_start = self.pbuf.len();
+                alts = 0;
+                group_start = self.pbuf.len();
+                continue;
+            }
+
+            if c == b')' && self.options.extended {
+                let Some(outer) = groups.pop() else {
+                    return Err(badpat("Unmatched )", source, i));
+                };
+                if alts != 0 && self.pbuf.len() == branch_start {
+                    return Err(badpat("Empty alternative", source, i));
+                }
+                // Close the right alternatives.
+                for _ in 0..alts {
+                    self.store(ENDPAT)?;
+                }
+                if self.pbuf.len() == group_start {
+                    return Err(badpat("Empty group", source, i));
+                }
+                // Repeat the whole group, not its last pattern. A group of only
+                // anchors cannot be repeated, like an anchor.
+                pat_start = group_start;
+                repeatable = !only_anchors(&decode(&self.pbuf[group_start..], &mut 0));
+                (branch_start, alts, group_start, _) = outer;
+                continue;
+            }
+
             // Remember the start of the pattern, so it can be repeated.
             pat_start = self.pbuf.len();
+            repeatable = c != b'^' && c != b'$';
             // All the other cases.
             match c {
                 b'^' => self.store(BOL)?,
@@ -159,22 +551,42 @@ impl Compiler {
                         b'd' | b'D' => self.store(DIGIT)?,
                         b'n' | b'N' => self.store(NALPHA)?,
                         b' ' => self.store(PUNCT)?,
+                        b'p' | b'P' => self.store(APUNCT)?,
                         _ => return Err(badpat("Unknown : type", source, i)),
                     }
                 }
                 mut c => {
                     if c == b'\\' && i < source.len() {
-                        c = source[i];
-                        i += 1;
+                        (c, i) = unescape(source, i)?;
                     }
                     self.store(CHAR)?;
-                    self.store(c.to_ascii_lowercase())?;
+                    self.store(self.fold(c))?;
                 }
             }
         }
 
+        if let Some(&(_, _, _, open)) = groups.last() {
+            return Err(badpat("Unterminated group", source, open));
+        }
+        if alts != 0 && self.pbuf.len() == branch_start {
+            return Err(badpat("Empty alternative", source, source.len()));
+        }
+        // Close the right alternatives.
+        for _ in 0..alts {
+            self.store(ENDPAT)?;
+        }
         self.store(ENDPAT)?;
 
+        if self.options.linear_only {
+            let complexity = self.complexity_class();
+            if complexity != Complexity::Linear {
+                return Err(Error {
+                    msg: "Pattern may backtrack superlinearly",
+                    kind: ErrorKind::NotLinear(complexity),
+                });
+            }
+        }
+
         if self.debug != 0 {
             let mut stdout = stdout().lock();
             for &c in &self.pbuf {
@@ -187,6 +599,9 @@ impl Compiler {
             }
             // Emulate the NUL terminator.
             stdout.write_all(b"\\0 \n").unwrap();
+            if self.debug > 1 {
+                self.tree(&mut stdout).unwrap();
+            }
         }
         Ok(())
     }
@@ -201,6 +616,10 @@ impl Compiler {
         let class_start = self.pbuf.len();
         self.store(0)?; // Byte count
 
+        // The last member, if it was a char, which can start a range. A dash
+        // directly after a range is a literal, so `[a-z-0]` is `a-z`, `-`, and
+        // `0`, instead of taking `z` as the start of another range.
+        let mut last_char: Option<(usize, u8)> = None;
         loop {
             if i >= source.len() {
                 return Err(badpat("Unterminated class", source, i));
@@ -215,39 +634,76 @@ impl Compiler {
                 if i >= source.len() {
                     return Err(badpat("Class terminates badly", source, i));
                 }
-                self.store(source[i].to_ascii_lowercase())?;
-                i += 1;
-            } else if c == b'-'
-                && (self.pbuf.len() - class_start) > 1
-                && i < source.len()
-                && source[i] != b']'
-            {
+                let c;
+                (c, i) = unescape(source, i)?;
+                last_char = Some((self.pbuf.len(), self.fold_class(c)));
+                self.store_member(self.fold_class(c))?;
+            } else if c == b'-' && i < source.len() && source[i] != b']' {
+                let Some((low_start, low)) = last_char.take() else {
+                    // Store a literal dash.
+                    last_char = Some((self.pbuf.len(), c));
+                    self.store_member(c)?;
+                    continue;
+                };
                 // Store a char range.
-                // BUG: Parses incorrectly when a range is followed by a dash.
-                let low = self.pbuf.pop().unwrap();
+                self.pbuf.truncate(low_start);
                 self.store(RANGE)?;
                 self.store(low)?;
-                let high = source[i];
-                self.store(high.to_ascii_lowercase())?;
+                // Like grep.c, the end of a range is taken as is, so `[a-\]`
+                // ends in a backslash, except that an octal escape is read.
+                let mut high = source[i];
                 i += 1;
+                if high == b'\\' && matches!(source.get(i), Some(b'0'..=b'7')) {
+                    (high, i) = unescape(source, i)?;
+                }
+                self.store(self.fold_class(high))?;
             } else {
                 // Store a literal char.
-                // BUG: U+000E cannot be stored literally, because it will be
-                // matched as RANGE as both are stored as 15.
-                self.store(c.to_ascii_lowercase())?;
+                last_char = Some((self.pbuf.len(), self.fold_class(c)));
+                self.store_member(self.fold_class(c))?;
             }
         }
 
+        // The byte count includes itself, so an empty class has a count of 1.
         let len = self.pbuf.len() - class_start;
         if len >= 256 {
             return Err(badpat("Class too large", source, i));
-        } else if len == 0 {
+        } else if len == 1 {
             return Err(badpat("Empty class", source, i));
         }
         self.pbuf[class_start] = len as u8;
         Ok(i)
     }
 
+    /// Folds the case of a literal byte for storing in the pattern.
+    fn fold(&self, c: u8) -> u8 {
+        if self.options.case_insensitive {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        }
+    }
+
+    /// Folds the case of a class member for storing in the pattern.
+    fn fold_class(&self, c: u8) -> u8 {
+        if self.options.fold_classes {
+            self.fold(c)
+        } else {
+            c
+        }
+    }
+
+    /// Stores a literal class member. Since the matcher takes any member equal
+    /// to `RANGE` as the start of a range, such a byte is stored as the
+    /// single-byte range `RANGE c c`.
+    fn store_member(&mut self, c: u8) -> Result<(), Error> {
+        if c == RANGE {
+            self.store(RANGE)?;
+            self.store(c)?;
+        }
+        self.store(c)
+    }
+
     fn store(&mut self, op: u8) -> Result<(), Error> {
         if self.pbuf.len() >= PMAX {
             return Err(error("Pattern too complex"));
@@ -255,6 +711,743 @@ impl Compiler {
         self.pbuf.push(op);
         Ok(())
     }
+
+    fn store_all(&mut self, ops: &[u8]) -> Result<(), Error> {
+        ops.iter().try_for_each(|&op| self.store(op))
+    }
+
+    /// Stores a repetition of a pattern.
+    fn store_repeat(&mut self, op: u8, pat: &[u8]) -> Result<(), Error> {
+        self.store(op)?;
+        self.store_all(pat)?;
+        self.store(ENDPAT)
+    }
+
+    /// Returns the set of bytes matched by the compiled pattern, when it
+    /// consists of a single class. When classes are case-insensitive, lines
+    /// are lowercased when matching, so an uppercase byte is a member when its
+    /// lowercase form is.
+    pub fn class_members(&self) -> Option<[bool; 256]> {
+        let (&op, rest) = self.pbuf.split_first()?;
+        if op != CLASS && op != NCLASS {
+            return None;
+        }
+        let n = *rest.first()? as usize;
+        if rest.get(n..) != Some(&[ENDPAT]) {
+            return None;
+        }
+        let mut set = [false; 256];
+        for item in class_items(&rest[1..n]) {
+            match item {
+                ClassItem::Char(c) => set[c as usize] = true,
+                ClassItem::Range(low, high) => {
+                    for c in low..=high {
+                        set[c as usize] = true;
+                    }
+                }
+            }
+        }
+        Some(array::from_fn(|b| {
+            set[self.fold_class(b as u8) as usize] != (op == NCLASS)
+        }))
+    }
+
+    /// Returns the classes in the compiled pattern, in order.
+    pub fn classes(&self) -> impl Iterator<Item = ClassInfo> {
+        let mut classes = Vec::new();
+        collect_classes(&decode(&self.pbuf, &mut 0), &mut classes);
+        classes.into_iter()
+    }
+
+    /// Estimates the worst-case cost of matching the compiled pattern with a
+    /// backtracking matcher, from its structure. The estimate is conservative:
+    /// a pattern may be cheaper than its class on real input.
+    pub fn complexity_class(&self) -> Complexity {
+        let mut nested = false;
+        let unbounded = count_unbounded(&decode(&self.pbuf, &mut 0), &mut nested);
+        if nested {
+            Complexity::Exponential
+        } else if unbounded > 1 {
+            Complexity::Polynomial
+        } else {
+            Complexity::Linear
+        }
+    }
+
+    /// Combines two compiled patterns into one that matches either, by splicing
+    /// them under an `ALT` instead of recompiling. Both must fold case alike.
+    /// The result uses extended syntax, so its decompiled source does too.
+    pub fn or(&self, other: &Compiler) -> Result<Compiler, Error> {
+        if self.options.case_insensitive != other.options.case_insensitive
+            || self.options.fold_classes != other.options.fold_classes
+        {
+            return Err(error("Patterns fold case differently"));
+        }
+        // Each pattern already ends with the ENDPAT that closes its side.
+        if 1 + self.pbuf.len() + other.pbuf.len() + 1 > PMAX {
+            return Err(error("Pattern too complex"));
+        }
+        let mut pbuf = Vec::with_capacity(PMAX);
+        pbuf.push(ALT);
+        pbuf.extend_from_slice(&self.pbuf);
+        pbuf.extend_from_slice(&other.pbuf);
+        pbuf.push(ENDPAT);
+        Ok(Compiler {
+            debug: self.debug,
+            options: CompileOptions {
+                extended: true,
+                ..self.options.clone()
+            },
+            pbuf,
+        })
+    }
+
+    /// Rewrites the compiled pattern into an equivalent one that is no larger.
+    /// A class of a single char becomes a literal, duplicate class members and
+    /// empty ranges are dropped, and an alternation of identical alternatives
+    /// becomes one. An alternative that can never match, because it requires
+    /// a class of only empty ranges like `[z-a]`, is dropped, and alternatives
+    /// of single chars or classes merge into one class.
+    pub fn minimize(&mut self) {
+        let mut i = 0;
+        let nodes = decode(&self.pbuf, &mut i);
+        // A class can only become a literal when both fold case alike.
+        let class_as_char = self.options.fold_classes || !self.options.case_insensitive;
+        let mut pbuf = Vec::with_capacity(self.pbuf.capacity());
+        encode_minimized(&nodes, class_as_char, &mut pbuf);
+        pbuf.push(ENDPAT);
+        pbuf.extend_from_slice(&self.pbuf[i..]);
+        self.pbuf = pbuf;
+    }
+
+    /// Prints the compiled pattern as a tree, with one opcode per line and the
+    /// sub-patterns of repetitions and alternations indented beneath them.
+    pub fn tree<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write_tree(&decode(&self.pbuf, &mut 0), 0, &mut w)
+    }
+
+    /// Reconstructs source for the compiled pattern, which compiles back to
+    /// the same pattern with the same options. Literals are lowercase when
+    /// compiled case-insensitively, and special or unprintable chars are
+    /// escaped. Groups are only produced for patterns compiled with extended
+    /// syntax, and since `{0}` leaves nothing to repeat, a repetition is never
+    /// empty.
+    pub fn decompile(&self) -> Vec<u8> {
+        let mut source = Vec::new();
+        decompile_nodes(&decode(&self.pbuf, &mut 0), &mut source);
+        source
+    }
+
+    /// Returns the fewest bytes any match of the compiled pattern consumes.
+    /// Lines shorter than this cannot match.
+    pub fn min_len(&self) -> usize {
+        min_len(&decode(&self.pbuf, &mut 0))
+    }
+
+    /// Returns the literal bytes every match must start with, after any `^`.
+    /// When compiled case-insensitively, they are lowercase and should be
+    /// compared against lowercased text.
+    pub fn literal_prefix(&self) -> Vec<u8> {
+        literal_prefix(&decode(&self.pbuf, &mut 0))
+    }
+
+    /// Returns whether the compiled pattern begins with `^`, so it can only
+    /// match at the start of a line.
+    pub fn is_anchored_start(&self) -> bool {
+        is_anchored_start(&decode(&self.pbuf, &mut 0))
+    }
+
+    /// Returns whether the compiled pattern ends with `$`, so it can only match
+    /// at the end of a line.
+    pub fn is_anchored_end(&self) -> bool {
+        is_anchored_end(&decode(&self.pbuf, &mut 0))
+    }
+}
+
+/// A decoded element of a compiled pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node<'a> {
+    /// An opcode without operands, such as `BOL` or `ALPHA`.
+    Op(u8),
+    /// A literal char.
+    Char(u8),
+    /// A class or negated class with its members, excluding the byte count.
+    Class { negated: bool, members: &'a [u8] },
+    /// A `STAR`, `PLUS`, `PPLUS`, or `MINUS` repetition of a sub-pattern.
+    Repeat { op: u8, sub: Vec<Node<'a>> },
+    /// An alternation of two sub-patterns, either of which is followed by the
+    /// rest of the enclosing pattern.
+    Alt(Vec<Node<'a>>, Vec<Node<'a>>),
+}
+
+/// Decodes the compiled pattern starting at `pbuf[*i]` through its `ENDPAT`,
+/// advancing `i` past it. A truncated pattern, such as one left behind by a
+/// failed compile, decodes up to where it is cut off.
+fn decode<'a>(pbuf: &'a [u8], i: &mut usize) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    while let Some(&op) = pbuf.get(*i) {
+        *i += 1;
+        let node = match op {
+            ENDPAT => break,
+            CHAR => {
+                let Some(&c) = pbuf.get(*i) else { break };
+                *i += 1;
+                Node::Char(c)
+            }
+            CLASS | NCLASS => {
+                let Some(&n) = pbuf.get(*i) else { break };
+                let Some(members) = pbuf.get(*i + 1..*i + n as usize) else {
+                    break;
+                };
+                *i += n as usize;
+                Node::Class {
+                    negated: op == NCLASS,
+                    members,
+                }
+            }
+            STAR | PLUS | PPLUS | MINUS => Node::Repeat {
+                op,
+                sub: decode(pbuf, i),
+            },
+            ALT => {
+                let left = decode(pbuf, i);
+                Node::Alt(left, decode(pbuf, i))
+            }
+            _ => Node::Op(op),
+        };
+        nodes.push(node);
+    }
+    nodes
+}
+
+fn collect_classes(nodes: &[Node<'_>], classes: &mut Vec<ClassInfo>) {
+    for node in nodes {
+        match node {
+            Node::Class { negated, members } => {
+                let mut class = ClassInfo {
+                    negated: *negated,
+                    chars: Vec::new(),
+                    ranges: Vec::new(),
+                };
+                for item in class_items(members) {
+                    match item {
+                        ClassItem::Char(c) | ClassItem::Range(c @ RANGE, RANGE) => {
+                            class.chars.push(c)
+                        }
+                        ClassItem::Range(low, high) => class.ranges.push((low, high)),
+                    }
+                }
+                classes.push(class);
+            }
+            Node::Repeat { sub, .. } => collect_classes(sub, classes),
+            Node::Alt(left, right) => {
+                collect_classes(left, classes);
+                collect_classes(right, classes);
+            }
+            Node::Op(_) | Node::Char(_) => {}
+        }
+    }
+}
+
+/// Counts the unbounded repetitions that can apply in sequence, setting
+/// `nested` if any repeats a pattern of varying length. A possessive
+/// repetition never backtracks into itself, so it is not counted.
+fn count_unbounded(nodes: &[Node<'_>], nested: &mut bool) -> usize {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Repeat {
+                op: STAR | PLUS,
+                sub,
+            } => {
+                if max_len(sub) != Some(min_len(sub)) {
+                    *nested = true;
+                }
+                1 + count_unbounded(sub, nested)
+            }
+            Node::Repeat { sub, .. } => count_unbounded(sub, nested),
+            Node::Alt(left, right) => {
+                count_unbounded(left, nested).max(count_unbounded(right, nested))
+            }
+            Node::Op(_) | Node::Char(_) | Node::Class { .. } => 0,
+        })
+        .sum()
+}
+
+fn encode_minimized(nodes: &[Node<'_>], class_as_char: bool, pbuf: &mut Vec<u8>) {
+    for node in nodes {
+        match node {
+            Node::Op(op) => pbuf.push(*op),
+            Node::Char(c) => pbuf.extend_from_slice(&[CHAR, *c]),
+            Node::Class { negated, members } => {
+                // Dropping members only shrinks a class, so it always fits.
+                encode_class(
+                    *negated,
+                    class_items(members).collect(),
+                    class_as_char,
+                    pbuf,
+                );
+            }
+            Node::Repeat { op, sub } => {
+                pbuf.push(*op);
+                encode_minimized(sub, class_as_char, pbuf);
+                pbuf.push(ENDPAT);
+            }
+            Node::Alt(left, right) if left == right => {
+                encode_minimized(left, class_as_char, pbuf);
+            }
+            // An alternative that can never match is dropped.
+            Node::Alt(left, right) if matches_nothing(left) && !matches_nothing(right) => {
+                encode_minimized(right, class_as_char, pbuf);
+            }
+            Node::Alt(left, right) if matches_nothing(right) && !matches_nothing(left) => {
+                encode_minimized(left, class_as_char, pbuf);
+            }
+            Node::Alt(left, right) => {
+                // Alternatives of single chars or classes merge into one class,
+                // when it is not too large.
+                let mut items = Vec::new();
+                if class_union(slice::from_ref(node), class_as_char, &mut items)
+                    && encode_class(false, items, class_as_char, pbuf)
+                {
+                    continue;
+                }
+                pbuf.push(ALT);
+                encode_minimized(left, class_as_char, pbuf);
+                pbuf.push(ENDPAT);
+                encode_minimized(right, class_as_char, pbuf);
+                pbuf.push(ENDPAT);
+            }
+        }
+    }
+}
+
+/// Encodes a class with its duplicate members and empty ranges removed, or a
+/// literal when it has a single char. Returns false, leaving `pbuf` unchanged,
+/// when the class is too large.
+fn encode_class(
+    negated: bool,
+    items: Vec<ClassItem>,
+    class_as_char: bool,
+    pbuf: &mut Vec<u8>,
+) -> bool {
+    let is_empty = |item: &ClassItem| matches!(*item, ClassItem::Range(low, high) if low > high);
+    let mut members = Vec::new();
+    for &item in &items {
+        if !members.contains(&item) && !is_empty(&item) {
+            members.push(item);
+        }
+    }
+    // A class of only empty ranges matches nothing, so keep one of them.
+    if members.is_empty() {
+        members.extend(items.first());
+    }
+    let single = match members[..] {
+        [ClassItem::Char(c)] => Some(c),
+        [ClassItem::Range(low, high)] if low == high => Some(low),
+        _ => None,
+    };
+    match single {
+        Some(c) if !negated && class_as_char => pbuf.extend_from_slice(&[CHAR, c]),
+        _ => {
+            let start = pbuf.len();
+            pbuf.push(if negated { NCLASS } else { CLASS });
+            pbuf.push(0);
+            for item in members {
+                match item {
+                    ClassItem::Char(c) if c != RANGE => pbuf.push(c),
+                    ClassItem::Char(c) => pbuf.extend_from_slice(&[RANGE, c, c]),
+                    ClassItem::Range(low, high) => pbuf.extend_from_slice(&[RANGE, low, high]),
+                }
+            }
+            let len = pbuf.len() - (start + 1);
+            if len >= 256 {
+                pbuf.truncate(start);
+                return false;
+            }
+            pbuf[start + 1] = len as u8;
+        }
+    }
+    true
+}
+
+/// Collects the members of a pattern that matches exactly one byte from a
+/// set, which is a single char, a class, or an alternation of them. Returns
+/// false for any other pattern.
+fn class_union(nodes: &[Node<'_>], class_as_char: bool, items: &mut Vec<ClassItem>) -> bool {
+    match nodes {
+        [Node::Char(c)] if class_as_char => {
+            items.push(ClassItem::Char(*c));
+            true
+        }
+        [Node::Class {
+            negated: false,
+            members,
+        }] => {
+            items.extend(class_items(members));
+            true
+        }
+        [Node::Alt(left, right)] => {
+            class_union(left, class_as_char, items) && class_union(right, class_as_char, items)
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether a pattern can never match, because it requires a class
+/// with only empty ranges, like `[z-a]`.
+fn matches_nothing(nodes: &[Node<'_>]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Class {
+            negated: false,
+            members,
+        } => class_items(members)
+            .all(|item| matches!(item, ClassItem::Range(low, high) if low > high)),
+        Node::Repeat {
+            op: PLUS | PPLUS,
+            sub,
+        } => matches_nothing(sub),
+        Node::Alt(left, right) => matches_nothing(left) && matches_nothing(right),
+        _ => false,
+    })
+}
+
+fn min_len(nodes: &[Node<'_>]) -> usize {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Op(BOL | EOL) => 0,
+            Node::Op(_) | Node::Char(_) | Node::Class { .. } => 1,
+            Node::Repeat {
+                op: PLUS | PPLUS,
+                sub,
+            } => min_len(sub),
+            Node::Repeat { .. } => 0,
+            Node::Alt(left, right) => min_len(left).min(min_len(right)),
+        })
+        .sum()
+}
+
+/// Returns the most bytes any match of a pattern consumes, or `None` if it is
+/// unbounded.
+fn max_len(nodes: &[Node<'_>]) -> Option<usize> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Op(BOL | EOL) => Some(0),
+            Node::Op(_) | Node::Char(_) | Node::Class { .. } => Some(1),
+            Node::Repeat { op: MINUS, sub } => max_len(sub),
+            Node::Repeat { .. } => None,
+            Node::Alt(left, right) => Some(max_len(left)?.max(max_len(right)?)),
+        })
+        .sum()
+}
+
+fn literal_prefix(nodes: &[Node<'_>]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    for node in nodes.iter().skip_while(|node| **node == Node::Op(BOL)) {
+        match node {
+            Node::Char(c) => prefix.push(*c),
+            Node::Alt(left, right) => {
+                let (left, right) = (literal_prefix(left), literal_prefix(right));
+                let common = left.iter().zip(&right).take_while(|(l, r)| l == r);
+                prefix.extend(common.map(|(&c, _)| c));
+                break;
+            }
+            _ => break,
+        }
+    }
+    prefix
+}
+
+fn is_anchored_start(nodes: &[Node<'_>]) -> bool {
+    match nodes.first() {
+        Some(Node::Op(BOL)) => true,
+        Some(Node::Alt(left, right)) => is_anchored_start(left) && is_anchored_start(right),
+        _ => false,
+    }
+}
+
+fn is_anchored_end(nodes: &[Node<'_>]) -> bool {
+    match nodes.last() {
+        Some(Node::Op(EOL)) => true,
+        Some(Node::Alt(left, right)) => is_anchored_end(left) && is_anchored_end(right),
+        _ => false,
+    }
+}
+
+/// Returns whether a pattern consists of only `^` and `$` anchors.
+fn only_anchors(nodes: &[Node<'_>]) -> bool {
+    nodes.iter().all(|node| match node {
+        Node::Op(BOL | EOL) => true,
+        Node::Alt(left, right) => only_anchors(left) && only_anchors(right),
+        _ => false,
+    })
+}
+
+fn decompile_nodes(nodes: &[Node<'_>], source: &mut Vec<u8>) {
+    for node in nodes {
+        match *node {
+            Node::Op(op) => source.extend_from_slice(match op {
+                BOL => b"^",
+                EOL => b"$",
+                ANY => b".",
+                ALPHA => b":a",
+                DIGIT => b":d",
+                NALPHA => b":n",
+                PUNCT => b": ",
+                APUNCT => b":p",
+                _ => b"",
+            }),
+            Node::Char(c) => decompile_char(c, b"\\^$.[:*+-|{() #", source),
+            Node::Class { negated, members } => {
+                source.push(b'[');
+                if negated {
+                    source.push(b'^');
+                }
+                for item in class_items(members) {
+                    match item {
+                        ClassItem::Range(RANGE, RANGE) => {
+                            decompile_char(RANGE, b"", source);
+                        }
+                        ClassItem::Char(c) => decompile_char(c, b"\\^-]", source),
+                        ClassItem::Range(low, high) => {
+                            decompile_char(low, b"\\^-]", source);
+                            source.push(b'-');
+                            // The end of a range is only unescaped when octal.
+                            if high == b'\\' || high == b']' {
+                                decompile_octal(high, source);
+                            } else {
+                                decompile_char(high, b"", source);
+                            }
+                        }
+                    }
+                }
+                source.push(b']');
+            }
+            Node::Repeat { op, ref sub } => {
+                decompile_group(sub, source);
+                source.extend_from_slice(match op {
+                    STAR => b"*",
+                    PLUS => b"+",
+                    PPLUS => b"++",
+                    _ => b"-",
+                });
+            }
+            // An alternation alone needs no group, which is the case at the
+            // top level.
+            Node::Alt(ref left, ref right) if nodes.len() == 1 => {
+                // Alternations nest to the right, so one on the left needs a
+                // group.
+                if let [Node::Alt(..)] = left[..] {
+                    decompile_group(left, source);
+                } else {
+                    decompile_nodes(left, source);
+                }
+                source.push(b'|');
+                decompile_nodes(right, source);
+            }
+            Node::Alt(..) => decompile_group(slice::from_ref(node), source),
+        }
+    }
+}
+
+fn write_tree<W: Write>(nodes: &[Node<'_>], depth: usize, w: &mut W) -> io::Result<()> {
+    for node in nodes {
+        write!(w, "{:1$}", "", depth * 2)?;
+        match node {
+            Node::Op(op) => {
+                let name = match *op {
+                    BOL => "BOL",
+                    EOL => "EOL",
+                    ANY => "ANY",
+                    ALPHA => "ALPHA",
+                    DIGIT => "DIGIT",
+                    NALPHA => "NALPHA",
+                    PUNCT => "PUNCT",
+                    APUNCT => "APUNCT",
+                    _ => "?",
+                };
+                writeln!(w, "{name}")?;
+            }
+            Node::Char(c) => writeln!(w, "CHAR '{}'", c.escape_ascii())?,
+            Node::Class { negated, members } => {
+                w.write_all(if *negated { b"NCLASS" } else { b"CLASS" })?;
+                for item in class_items(members) {
+                    match item {
+                        ClassItem::Char(c) | ClassItem::Range(c @ RANGE, RANGE) => {
+                            write!(w, " '{}'", c.escape_ascii())?
+                        }
+                        ClassItem::Range(low, high) => {
+                            write!(w, " '{}'-'{}'", low.escape_ascii(), high.escape_ascii())?
+                        }
+                    }
+                }
+                writeln!(w)?;
+            }
+            Node::Repeat { op, sub } => {
+                let name = match *op {
+                    STAR => "STAR",
+                    PLUS => "PLUS",
+                    PPLUS => "PPLUS",
+                    _ => "MINUS",
+                };
+                writeln!(w, "{name}")?;
+                write_tree(sub, depth + 1, w)?;
+            }
+            Node::Alt(left, right) => {
+                writeln!(w, "ALT")?;
+                write_tree(left, depth + 1, w)?;
+                writeln!(w, "{:1$}|", "", depth * 2 + 2)?;
+                write_tree(right, depth + 1, w)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a pattern as a group, unless it is a single element that can be
+/// repeated by itself.
+fn decompile_group(nodes: &[Node<'_>], source: &mut Vec<u8>) {
+    // A repetition or alternation alone still needs a group, or it would
+    // merge with the operator after it.
+    if let [node] = nodes {
+        if !matches!(node, Node::Repeat { .. } | Node::Alt(..)) {
+            return decompile_nodes(nodes, source);
+        }
+    }
+    source.push(b'(');
+    decompile_nodes(nodes, source);
+    source.push(b')');
+}
+
+/// Writes a literal char, escaping it if it is in `special` and writing it
+/// as a three-digit octal escape if it is unprintable.
+fn decompile_char(c: u8, special: &[u8], source: &mut Vec<u8>) {
+    if !c.is_ascii_graphic() && c != b' ' {
+        decompile_octal(c, source);
+    } else {
+        if special.contains(&c) {
+            source.push(b'\\');
+        }
+        source.push(c);
+    }
+}
+
+/// Writes a char as a three-digit octal escape.
+fn decompile_octal(c: u8, source: &mut Vec<u8>) {
+    source.extend_from_slice(&[b'\\', b'0' + (c >> 6), b'0' + (c >> 3 & 7), b'0' + (c & 7)]);
+}
+
+/// The worst-case cost of backtracking when matching a pattern against a line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Complexity {
+    /// At most one unbounded repetition applies at a time, like `a*b`.
+    Linear,
+    /// Several unbounded repetitions follow each other, like `a*b*`, so each
+    /// can backtrack over the others.
+    Polynomial,
+    /// An unbounded repetition repeats a pattern of varying length, like
+    /// `(a*)*` or `(a|ab)*`, so there are exponentially many ways to split a
+    /// line between iterations.
+    Exponential,
+}
+
+/// A class in a compiled pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassInfo {
+    /// Whether the class is negated, as in `[^...]`.
+    pub negated: bool,
+    /// The chars in the class, excluding ranges.
+    pub chars: Vec<u8>,
+    /// The inclusive ranges in the class.
+    pub ranges: Vec<(u8, u8)>,
+}
+
+/// A member of a compiled class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClassItem {
+    Char(u8),
+    Range(u8, u8),
+}
+
+/// Decodes the members of a compiled class, excluding the byte count. Like
+/// the matcher, any byte equal to `RANGE` starts a range.
+fn class_items(members: &[u8]) -> impl Iterator<Item = ClassItem> + '_ {
+    let mut i = 0;
+    iter::from_fn(move || {
+        let c = *members.get(i)?;
+        if c == RANGE {
+            let item = ClassItem::Range(*members.get(i + 1)?, *members.get(i + 2)?);
+            i += 3;
+            Some(item)
+        } else {
+            i += 1;
+            Some(ClassItem::Char(c))
+        }
+    })
+}
+
+/// Reads the escaped char after a backslash, starting at `source[i]`. One to
+/// three octal digits are an octal escape for a byte; any other char is
+/// quoted. Returns the char and the offset after the escape.
+fn unescape(source: &[u8], mut i: usize) -> Result<(u8, usize), Error> {
+    let start = i;
+    let mut value = 0u32;
+    while i < source.len() && i - start < 3 && matches!(source[i], b'0'..=b'7') {
+        value = value * 8 + (source[i] - b'0') as u32;
+        i += 1;
+    }
+    if i == start {
+        return Ok((source[i], i + 1));
+    }
+    if value > 0o377 {
+        return Err(badpat("Octal escape out of range", source, i));
+    }
+    Ok((value as u8, i))
+}
+
+/// Parses the bounds of a `{n}`, `{n,}`, or `{n,m}` repetition, starting
+/// after the `{`. Returns the bounds, where `None` is unbounded, and the offset
+/// after the `}`.
+fn bounds(source: &[u8], mut i: usize) -> Result<(usize, Option<usize>, usize), Error> {
+    let number = |i: &mut usize| -> Result<Option<usize>, Error> {
+        let start = *i;
+        let mut n = 0usize;
+        while let Some(&c @ b'0'..=b'9') = source.get(*i) {
+            *i += 1;
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_add((c - b'0') as usize))
+                .ok_or_else(|| badpat("Repetition count too large", source, *i))?;
+        }
+        Ok((*i != start).then_some(n))
+    };
+    let Some(min) = number(&mut i)? else {
+        return Err(badpat(
+            "Bad repetition bounds",
+            source,
+            (i + 1).min(source.len()),
+        ));
+    };
+    let max = if source.get(i) == Some(&b',') {
+        i += 1;
+        number(&mut i)?
+    } else {
+        Some(min)
+    };
+    if source.get(i) != Some(&b'}') {
+        return Err(badpat(
+            "Unterminated repetition",
+            source,
+            (i + 1).min(source.len()),
+        ));
+    }
+    i += 1;
+    if max.is_some_and(|max| max < min) {
+        return Err(badpat("Bad repetition bounds", source, i));
+    }
+    Ok((min, max, i))
 }
 
 fn badpat(msg: &'static str, source: &[u8], offset: usize) -> Error {
@@ -273,3 +1466,782 @@ fn error(msg: &'static str) -> Error {
         kind: ErrorKind::Other,
     }
 }
+
+/// Splits a buffer into lines ending with `terminator`, like lines read by
+/// `fgets`. A final line without a terminator is still yielded, but an empty
+/// buffer yields no lines.
+pub fn split_lines(
+    buf: &[u8],
+    terminator: u8,
+    keep_terminator: bool,
+) -> impl Iterator<Item = &[u8]> {
+    buf.split_inclusive(move |&b| b == terminator)
+        .map(move |line| match line.split_last() {
+            Some((&last, rest)) if !keep_terminator && last == terminator => rest,
+            _ => line,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiler(source: &[u8], options: CompileOptions) -> Result<Compiler, Error> {
+        let mut compiler = Compiler::with_options(0, options);
+        compiler.compile(source)?;
+        Ok(compiler)
+    }
+
+    fn compile(source: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(compiler(source, CompileOptions::default())?.pbuf)
+    }
+
+    fn error_msg(result: Result<Vec<u8>, Error>) -> &'static str {
+        result.unwrap_err().msg
+    }
+
+    fn extended() -> CompileOptions {
+        CompileOptions {
+            extended: true,
+            ..CompileOptions::default()
+        }
+    }
+
+    fn compile_extended(source: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(compiler(source, extended())?.pbuf)
+    }
+
+    #[test]
+    fn source_too_long() {
+        let options = CompileOptions {
+            max_source_len: Some(4),
+            ..CompileOptions::default()
+        };
+        let err = compiler(b"abcde", options.clone()).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::SourceTooLong { len: 5, max: 4 }
+        ));
+        assert_eq!(
+            compiler(b"abcd", options).unwrap().pbuf,
+            [CHAR, b'a', CHAR, b'b', CHAR, b'c', CHAR, b'd', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn octal_escapes() {
+        assert_eq!(compile(b"\\101").unwrap(), [CHAR, b'a', ENDPAT]);
+        assert_eq!(
+            compile(b"[\\001-\\037]").unwrap(),
+            [CLASS, 4, RANGE, 0o001, 0o037, ENDPAT],
+        );
+        assert_eq!(error_msg(compile(b"\\400")), "Octal escape out of range");
+    }
+
+    #[test]
+    fn class_range_backslash_end() {
+        assert_eq!(
+            compile(b"[a-\\]").unwrap(),
+            [CLASS, 4, RANGE, b'a', b'\\', ENDPAT],
+        );
+        assert_eq!(
+            compile(b"[+-\\]x]").unwrap(),
+            [CLASS, 4, RANGE, b'+', b'\\', CHAR, b'x', CHAR, b']', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn decompile_escaped_range() {
+        let compiler = compiler(b"[\\001-\\037]", CompileOptions::default()).unwrap();
+        assert_eq!(compiler.decompile(), b"[\\001-\\037]");
+    }
+
+    #[test]
+    fn split_lines_terminated() {
+        let lines: Vec<&[u8]> = split_lines(b"a\nbc\n", b'\n', false).collect();
+        assert_eq!(lines, [&b"a"[..], b"bc"]);
+        let lines: Vec<&[u8]> = split_lines(b"a\nbc\n", b'\n', true).collect();
+        assert_eq!(lines, [&b"a\n"[..], b"bc\n"]);
+    }
+
+    #[test]
+    fn split_lines_unterminated() {
+        let lines: Vec<&[u8]> = split_lines(b"a\0\0bc", b'\0', false).collect();
+        assert_eq!(lines, [&b"a"[..], b"", b"bc"]);
+        let lines: Vec<&[u8]> = split_lines(b"a\0\0bc", b'\0', true).collect();
+        assert_eq!(lines, [&b"a\0"[..], b"\0", b"bc"]);
+    }
+
+    #[test]
+    fn split_lines_empty() {
+        assert_eq!(split_lines(b"", b'\n', false).count(), 0);
+        assert_eq!(split_lines(b"", b'\n', true).count(), 0);
+    }
+
+    #[test]
+    fn leading_repetition() {
+        for (source, op) in [(&b"*foo"[..], '*'), (b"+foo", '+'), (b"-foo", '-')] {
+            let err = compile(source).unwrap_err();
+            assert_eq!(err.msg, "Illegal occurrance op.");
+            assert!(matches!(err.kind, ErrorKind::BadPat { offset: 1, .. }));
+            assert_eq!(err.line_column(), Some((1, 1)));
+            assert!(err
+                .to_string()
+                .ends_with(&format!("Stopped at byte 1, '{op}'")));
+        }
+    }
+
+    fn members(source: &[u8], options: CompileOptions) -> Vec<u8> {
+        let members = compiler(source, options).unwrap().class_members().unwrap();
+        (0..=255).filter(|&c| members[c as usize]).collect()
+    }
+
+    #[test]
+    fn class_members_range() {
+        assert_eq!(members(b"[a-c]", CompileOptions::default()), b"ABCabc");
+        assert!(compiler(b"a", CompileOptions::default())
+            .unwrap()
+            .class_members()
+            .is_none());
+    }
+
+    #[test]
+    fn class_members_negated() {
+        let expected: Vec<u8> = (0..=255u8).filter(|c| !c.is_ascii_digit()).collect();
+        assert_eq!(members(b"[^0-9]", CompileOptions::default()), expected);
+    }
+
+    #[test]
+    fn class_members_case_sensitive() {
+        let options = CompileOptions {
+            case_insensitive: false,
+            fold_classes: false,
+            ..CompileOptions::default()
+        };
+        assert_eq!(members(b"[a-cX]", options), b"Xabc");
+    }
+
+    #[test]
+    fn linear_only() {
+        let options = CompileOptions {
+            linear_only: true,
+            ..CompileOptions::default()
+        };
+        let err = compiler(b"a*a*", options.clone()).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::NotLinear(Complexity::Polynomial)
+        ));
+        assert_eq!(
+            compiler(b"a*b", options).unwrap().pbuf,
+            [STAR, CHAR, b'a', ENDPAT, CHAR, b'b', ENDPAT],
+        );
+        assert!(compile(b"a*a*").is_ok());
+    }
+
+    #[test]
+    fn empty_class() {
+        assert_eq!(error_msg(compile(b"[]")), "Empty class");
+        assert_eq!(error_msg(compile(b"[^]")), "Empty class");
+    }
+
+    #[test]
+    fn class_trailing_dash() {
+        assert_eq!(
+            compile(b"[a-z-]").unwrap(),
+            [CLASS, 5, RANGE, b'a', b'z', b'-', ENDPAT],
+        );
+        assert_eq!(
+            compile(b"[a-z-0]").unwrap(),
+            [CLASS, 6, RANGE, b'a', b'z', b'-', b'0', ENDPAT],
+        );
+        assert_eq!(
+            compile(b"[-a-z]").unwrap(),
+            [CLASS, 5, b'-', RANGE, b'a', b'z', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn class_range_byte() {
+        assert_eq!(
+            compile(b"[\\016]").unwrap(),
+            [CLASS, 4, RANGE, RANGE, RANGE, ENDPAT],
+        );
+        assert_eq!(
+            compile(b"[\\016-a]").unwrap(),
+            [CLASS, 4, RANGE, RANGE, b'a', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(
+            compile_extended(b"cat|dog").unwrap(),
+            [
+                ALT, CHAR, b'c', CHAR, b'a', CHAR, b't', ENDPAT, //
+                CHAR, b'd', CHAR, b'o', CHAR, b'g', ENDPAT, ENDPAT,
+            ],
+        );
+        assert_eq!(
+            compile_extended(b"ab|cd|e").unwrap(),
+            [
+                ALT, CHAR, b'a', CHAR, b'b', ENDPAT, //
+                ALT, CHAR, b'c', CHAR, b'd', ENDPAT, //
+                CHAR, b'e', ENDPAT, ENDPAT, ENDPAT,
+            ],
+        );
+        assert_eq!(
+            compile(b"a|b").unwrap(),
+            [CHAR, b'a', CHAR, b'|', CHAR, b'b', ENDPAT]
+        );
+    }
+
+    #[test]
+    fn alternation_repetition() {
+        assert_eq!(
+            compile_extended(b"ab*|c+").unwrap(),
+            [
+                ALT, CHAR, b'a', STAR, CHAR, b'b', ENDPAT, ENDPAT, //
+                PLUS, CHAR, b'c', ENDPAT, ENDPAT, ENDPAT,
+            ],
+        );
+        assert_eq!(
+            error_msg(compile_extended(b"a|*b")),
+            "Illegal occurrance op."
+        );
+        assert_eq!(
+            error_msg(compile_extended(b"(*b)")),
+            "Illegal occurrance op."
+        );
+    }
+
+    #[test]
+    fn empty_alternative() {
+        for source in [&b"(|)"[..], b"(|)*", b"a|", b"|a", b"(a|)", b"a||b"] {
+            assert_eq!(error_msg(compile_extended(source)), "Empty alternative");
+        }
+    }
+
+    #[test]
+    fn repeat_opcode_bytes() {
+        // Literals equal to an opcode do not affect what can be repeated.
+        assert_eq!(
+            compile(b"a\\7*").unwrap(),
+            [CHAR, b'a', STAR, CHAR, STAR, ENDPAT, ENDPAT],
+        );
+        assert_eq!(
+            compile(b"\\11*").unwrap(),
+            [STAR, CHAR, MINUS, ENDPAT, ENDPAT]
+        );
+        assert_eq!(compile(b"\\2+").unwrap(), [PLUS, CHAR, BOL, ENDPAT, ENDPAT]);
+        assert_eq!(error_msg(compile(b"^*")), "Illegal occurrance op.");
+        assert_eq!(error_msg(compile(b"a**")), "Illegal occurrance op.");
+    }
+
+    #[test]
+    fn empty_bounded_repetition() {
+        assert_eq!(compile_extended(b"xa{0}").unwrap(), [CHAR, b'x', ENDPAT]);
+        assert_eq!(compile_extended(b"xa{0,0}").unwrap(), [CHAR, b'x', ENDPAT]);
+        assert_eq!(
+            error_msg(compile_extended(b"xa{0}{99999999999999}")),
+            "Illegal occurrance op.",
+        );
+        assert_eq!(
+            error_msg(compile_extended(b"xa{0}*")),
+            "Illegal occurrance op."
+        );
+        assert_eq!(
+            compile_extended(b"a{2}b{1,2}").unwrap(),
+            [
+                CHAR, b'a', CHAR, b'a', CHAR, b'b', //
+                MINUS, CHAR, b'b', ENDPAT, ENDPAT,
+            ],
+        );
+    }
+
+    fn round_trip(source: &[u8], options: CompileOptions) {
+        let compiled = compiler(source, options.clone()).unwrap();
+        let decompiled = compiled.decompile();
+        let recompiled = compiler(&decompiled, options).unwrap();
+        assert_eq!(
+            compiled.pbuf,
+            recompiled.pbuf,
+            "{:?} decompiled to {:?}",
+            source.escape_ascii().to_string(),
+            decompiled.escape_ascii().to_string(),
+        );
+    }
+
+    #[test]
+    fn decompile_round_trip() {
+        let sources: &[&[u8]] = &[
+            b"abc",
+            b"^a.b$",
+            b"Hello",
+            b"[a-z]x[^0-9]",
+            b"[-a-z-][\\]\\^][\\016-a][\\001-\\037]",
+            b":a:d:n: :p",
+            b"a*b+c-",
+            b"\\*\\+\\-\\.\\[\\:\\^\\$\\\\",
+            b"a|b(c){d}# e",
+            b"\\001\\377",
+            b"[a-\\][+-\\]x][!-]1]",
+            b"[a-\\1]",
+        ];
+        for &source in sources {
+            round_trip(source, CompileOptions::default());
+        }
+        let sources: &[&[u8]] = &[
+            b"cat|dog",
+            b"(ab)+c",
+            b"a(b|c)*d",
+            b"(a|b)|c",
+            b"a|(b|c)",
+            b"x{2,3}y{2,}z{3}",
+            b"((a|bc)d)-e",
+            b"(a*)*",
+            b"(a-)*",
+            b"(a*)-",
+            b"(a+)+",
+            b"(a+)++",
+            b"(a++)+",
+            b"\\|\\(\\)\\{",
+        ];
+        for &source in sources {
+            round_trip(source, extended());
+        }
+        let verbose = CompileOptions {
+            verbose: true,
+            ..CompileOptions::default()
+        };
+        round_trip(b"a\\ b\\#c # comment", verbose);
+        let case_sensitive = CompileOptions {
+            case_insensitive: false,
+            ..CompileOptions::default()
+        };
+        round_trip(b"Hello[A-Z]", case_sensitive);
+    }
+
+    #[test]
+    fn group_repetition() {
+        assert_eq!(
+            compile_extended(b"(ab)+").unwrap(),
+            [PLUS, CHAR, b'a', CHAR, b'b', ENDPAT, ENDPAT],
+        );
+        assert_eq!(
+            compile_extended(b"(ab)*c").unwrap(),
+            [STAR, CHAR, b'a', CHAR, b'b', ENDPAT, CHAR, b'c', ENDPAT],
+        );
+        assert_eq!(
+            compile_extended(b"(a(bc)-)*").unwrap(),
+            [
+                STAR, CHAR, b'a', MINUS, CHAR, b'b', CHAR, b'c', ENDPAT, ENDPAT, //
+                ENDPAT,
+            ],
+        );
+        assert_eq!(
+            compile_extended(b"(a|b)c").unwrap(),
+            [ALT, CHAR, b'a', ENDPAT, CHAR, b'b', ENDPAT, CHAR, b'c', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn group_errors() {
+        assert_eq!(error_msg(compile_extended(b"()")), "Empty group");
+        assert_eq!(error_msg(compile_extended(b"a)")), "Unmatched )");
+        for source in [
+            &b"(^)*"[..],
+            b"($)+",
+            b"(^$)-",
+            b"(^|$)*",
+            b"(^)*z*",
+            b"(^){2}",
+        ] {
+            assert_eq!(
+                error_msg(compile_extended(source)),
+                "Illegal occurrance op."
+            );
+        }
+        assert_eq!(
+            compile_extended(b"(^a)*").unwrap(),
+            [STAR, BOL, CHAR, b'a', ENDPAT, ENDPAT],
+        );
+        let err = compiler(b"a(b(c)d", extended()).unwrap_err();
+        assert_eq!(err.msg, "Unterminated group");
+        assert!(matches!(err.kind, ErrorKind::BadPat { offset: 2, .. }));
+        assert_eq!(err.line_column(), Some((1, 2)));
+    }
+
+    fn tree(source: &[u8], options: CompileOptions) -> String {
+        let mut out = Vec::new();
+        compiler(source, options).unwrap().tree(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn tree_group() {
+        assert_eq!(
+            tree(b"a(bc)*d", extended()),
+            "CHAR 'a'\nSTAR\n  CHAR 'b'\n  CHAR 'c'\nCHAR 'd'\n",
+        );
+    }
+
+    #[test]
+    fn tree_class_range_byte() {
+        assert_eq!(
+            tree(b"[a\\016]", CompileOptions::default()),
+            "CLASS 'a' '\\x0e'\n",
+        );
+    }
+
+    #[test]
+    fn possessive_plus() {
+        assert_eq!(
+            compile_extended(b"a++a").unwrap(),
+            [PPLUS, CHAR, b'a', ENDPAT, CHAR, b'a', ENDPAT],
+        );
+        assert_eq!(
+            compile_extended(b"(ab)++").unwrap(),
+            [PPLUS, CHAR, b'a', CHAR, b'b', ENDPAT, ENDPAT],
+        );
+        assert_eq!(
+            compile_extended(b"\\++").unwrap(),
+            [PLUS, CHAR, b'+', ENDPAT, ENDPAT],
+        );
+        let verbose = CompileOptions {
+            verbose: true,
+            ..extended()
+        };
+        assert_eq!(
+            compiler(b"a+ + # possessive", verbose.clone())
+                .unwrap()
+                .pbuf,
+            [PPLUS, CHAR, b'a', ENDPAT, ENDPAT],
+        );
+        for source in [&b"+"[..], b"+a", b"++", b"+(:"] {
+            assert_eq!(
+                error_msg(compile_extended(source)),
+                "Illegal occurrance op."
+            );
+            assert_eq!(
+                error_msg(compiler(source, verbose.clone()).map(|c| c.pbuf)),
+                "Illegal occurrance op.",
+            );
+        }
+        assert_eq!(
+            error_msg(compile_extended(b"a+++")),
+            "Illegal occurrance op."
+        );
+        assert_eq!(
+            error_msg(compile_extended(b"a*+")),
+            "Illegal occurrance op."
+        );
+        assert_eq!(error_msg(compile(b"a++")), "Illegal occurrance op.");
+        round_trip(b"a++b(cd)++", extended());
+        let compiler = compiler(b"a++a++", extended()).unwrap();
+        assert_eq!(compiler.min_len(), 2);
+        assert_eq!(compiler.complexity_class(), Complexity::Linear);
+        assert_eq!(tree(b"a++", extended()), "PPLUS\n  CHAR 'a'\n");
+    }
+
+    fn complexity(source: &[u8]) -> Complexity {
+        compiler(source, extended()).unwrap().complexity_class()
+    }
+
+    #[test]
+    fn complexity_class() {
+        assert_eq!(complexity(b"abc"), Complexity::Linear);
+        assert_eq!(complexity(b"a*"), Complexity::Linear);
+        assert_eq!(complexity(b"a*b"), Complexity::Linear);
+        assert_eq!(complexity(b"a*b*"), Complexity::Polynomial);
+        assert_eq!(complexity(b"(a*)*"), Complexity::Exponential);
+        assert_eq!(complexity(b"(a|ab)+"), Complexity::Exponential);
+        assert_eq!(complexity(b"(a-)*"), Complexity::Exponential);
+        // A repeated pattern of fixed length splits a line only one way.
+        assert_eq!(complexity(b"(a|b)*"), Complexity::Linear);
+        assert_eq!(complexity(b"(ab|c[de])+"), Complexity::Linear);
+        assert_eq!(complexity(b"(a|b)*c*"), Complexity::Polynomial);
+        let options = CompileOptions {
+            linear_only: true,
+            ..extended()
+        };
+        assert!(compiler(b"(a|b)*", options).is_ok());
+    }
+
+    #[test]
+    fn verbose() {
+        let verbose = CompileOptions {
+            verbose: true,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            compiler(b"a b # comment\n c", verbose.clone())
+                .unwrap()
+                .pbuf,
+            compile(b"abc").unwrap(),
+        );
+        assert_eq!(
+            compiler(b"a\\ b\\#", verbose.clone()).unwrap().pbuf,
+            [CHAR, b'a', CHAR, b' ', CHAR, b'b', CHAR, b'#', ENDPAT],
+        );
+        assert_eq!(
+            compiler(b"a : b", verbose).unwrap().pbuf,
+            [CHAR, b'a', PUNCT, CHAR, b'b', ENDPAT],
+        );
+    }
+
+    fn minimized(source: &[u8], options: CompileOptions) -> Vec<u8> {
+        let mut compiler = compiler(source, options.clone()).unwrap();
+        let len = compiler.pbuf.len();
+        let members = compiler.class_members();
+        compiler.minimize();
+        assert!(compiler.pbuf.len() <= len);
+        if let (Some(before), Some(after)) = (members, compiler.class_members()) {
+            assert_eq!(before, after);
+        }
+        let source = compiler.decompile();
+        round_trip(&source, options);
+        source
+    }
+
+    #[test]
+    fn minimize() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"[a]b", b"ab"),
+            (b"[a-a]", b"a"),
+            (b"[aab]", b"[ab]"),
+            (b"[a-cz-a]", b"[a-c]"),
+            (b"[z-a]", b"[z-a]"),
+            (b"[^a]|b", b"[^a]|b"),
+            (b"a|a", b"a"),
+            (b"a|b", b"[ab]"),
+            (b"[ab]|[bc]|d", b"[abcd]"),
+            (b"x(a|[b-d])y", b"x[ab-d]y"),
+            (b"[z-a]|b", b"b"),
+            (b"c|[z-a]", b"c"),
+            (b"(a[z-a]+)|bc", b"bc"),
+            (b"a[z-a]*|b", b"a[z-a]*|b"),
+            (b"ab|cd", b"ab|cd"),
+            (b"a*|b", b"a*|b"),
+            (b"(a|b)*c", b"[ab]*c"),
+            (b"\\016|a", b"[\\016a]"),
+        ];
+        for &(source, expected) in cases {
+            assert_eq!(
+                minimized(source, extended()).escape_ascii().to_string(),
+                expected.escape_ascii().to_string(),
+            );
+        }
+    }
+
+    #[test]
+    fn minimize_unfolded_classes() {
+        let options = CompileOptions {
+            extended: true,
+            fold_classes: false,
+            ..CompileOptions::default()
+        };
+        assert_eq!(minimized(b"A|[B]", options.clone()), b"a|[B]");
+        assert_eq!(minimized(b"[A]|[B]", options), b"[AB]");
+    }
+
+    #[test]
+    fn flag_help_matches_documentation() {
+        let flags: Vec<&str> = DOCUMENTATION
+            .lines()
+            .filter(|line| line.starts_with('-'))
+            .collect();
+        let help: Vec<String> = flag_help()
+            .iter()
+            .map(|h| format!("-{}      {}", h.flag, h.help))
+            .collect();
+        assert_eq!(flags, help);
+    }
+
+    #[test]
+    fn syntax_help_matches_patdoc() {
+        let syntax: Vec<&str> = PATDOC
+            .lines()
+            .filter_map(|line| {
+                if line.starts_with("x ") {
+                    Some("x")
+                } else {
+                    let line = line.strip_prefix('\'')?;
+                    Some(&line[..line[1..].find('\'')? + 1])
+                }
+            })
+            .collect();
+        let help: Vec<&str> = pattern_syntax_help().iter().map(|h| h.syntax).collect();
+        assert_eq!(syntax, help);
+    }
+
+    #[test]
+    fn bounded_repetition() {
+        assert_eq!(
+            compile_extended(b"a{2,3}").unwrap(),
+            [CHAR, b'a', CHAR, b'a', MINUS, CHAR, b'a', ENDPAT, ENDPAT],
+        );
+        assert_eq!(
+            compile_extended(b"a{1,}").unwrap(),
+            [CHAR, b'a', STAR, CHAR, b'a', ENDPAT, ENDPAT],
+        );
+        assert_eq!(
+            error_msg(compile_extended(b"a{3,1}")),
+            "Bad repetition bounds"
+        );
+    }
+
+    fn min_len(source: &[u8]) -> usize {
+        compiler(source, extended()).unwrap().min_len()
+    }
+
+    #[test]
+    fn min_len_counts_required_bytes() {
+        assert_eq!(min_len(b"fo*"), 1);
+        assert_eq!(min_len(b"fo+"), 2);
+        assert_eq!(min_len(b"fo-"), 1);
+        assert_eq!(min_len(b"..."), 3);
+        assert_eq!(min_len(b"^[ab]:d$"), 2);
+        assert_eq!(min_len(b"abc|d"), 1);
+        assert_eq!(min_len(b"x(ab|cde)+"), 3);
+    }
+
+    fn anchored(source: &[u8]) -> (bool, bool) {
+        let compiler = compiler(source, extended()).unwrap();
+        (compiler.is_anchored_start(), compiler.is_anchored_end())
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(anchored(b"^foo"), (true, false));
+        assert_eq!(anchored(b"foo$"), (false, true));
+        assert_eq!(anchored(b"^foo$"), (true, true));
+        assert_eq!(anchored(b"foo"), (false, false));
+        assert_eq!(anchored(b"^a|^b"), (true, false));
+        assert_eq!(anchored(b"^a|b"), (false, false));
+        assert_eq!(anchored(b"a$|b$"), (false, true));
+        assert_eq!(anchored(b"a$|b"), (false, false));
+    }
+
+    fn prefix(source: &[u8], options: CompileOptions) -> Vec<u8> {
+        compiler(source, options).unwrap().literal_prefix()
+    }
+
+    #[test]
+    fn literal_prefix() {
+        assert_eq!(prefix(b"^abc", extended()), b"abc");
+        assert_eq!(prefix(b"ab*", extended()), b"a");
+        assert_eq!(prefix(b"ab.c", extended()), b"ab");
+        assert_eq!(prefix(b"(ab|ac)d", extended()), b"a");
+        assert_eq!(prefix(b"a|b", extended()), b"");
+        assert_eq!(prefix(b"FOO", extended()), b"foo");
+        let case_sensitive = CompileOptions {
+            case_insensitive: false,
+            ..CompileOptions::default()
+        };
+        assert_eq!(prefix(b"FOO", case_sensitive), b"FOO");
+    }
+
+    fn classes(source: &[u8]) -> Vec<ClassInfo> {
+        compiler(source, extended()).unwrap().classes().collect()
+    }
+
+    #[test]
+    fn classes_in_order() {
+        assert_eq!(
+            classes(b"[a-z][0-9]"),
+            [
+                ClassInfo {
+                    negated: false,
+                    chars: vec![],
+                    ranges: vec![(b'a', b'z')],
+                },
+                ClassInfo {
+                    negated: false,
+                    chars: vec![],
+                    ranges: vec![(b'0', b'9')],
+                },
+            ],
+        );
+        assert_eq!(
+            classes(b"x[^ab]*|([c\\016])"),
+            [
+                ClassInfo {
+                    negated: true,
+                    chars: vec![b'a', b'b'],
+                    ranges: vec![],
+                },
+                ClassInfo {
+                    negated: false,
+                    chars: vec![b'c', RANGE],
+                    ranges: vec![],
+                },
+            ],
+        );
+        assert!(classes(b"abc").is_empty());
+    }
+
+    #[test]
+    fn case_sensitive() {
+        let options = CompileOptions {
+            case_insensitive: false,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            compiler(b"Hi[A-Z]", options).unwrap().pbuf,
+            [CHAR, b'H', CHAR, b'i', CLASS, 4, RANGE, b'A', b'Z', ENDPAT],
+        );
+        assert_eq!(
+            compile(b"Hi[A-Z]").unwrap(),
+            [CHAR, b'h', CHAR, b'i', CLASS, 4, RANGE, b'a', b'z', ENDPAT],
+        );
+    }
+
+    #[test]
+    fn line_column() {
+        let verbose = CompileOptions {
+            verbose: true,
+            ..extended()
+        };
+        let err = compiler(b"a\n(", verbose.clone()).unwrap_err();
+        assert_eq!(err.msg, "Unterminated group");
+        assert_eq!(err.line_column(), Some((2, 1)));
+        let err = compiler(b"a # ok\n\tb\n\t^*", verbose).unwrap_err();
+        assert_eq!(err.line_column(), Some((3, 3)));
+        let err = compile(b"a\t^*").unwrap_err();
+        assert_eq!(err.line_column(), Some((1, 4)));
+        let err = compile(b"\\101^*").unwrap_err();
+        assert_eq!(err.line_column(), Some((1, 6)));
+        assert_eq!(compile(b"[").unwrap_err().line_column(), Some((1, 1)));
+    }
+
+    #[test]
+    fn colon_classes() {
+        assert_eq!(compile(b":p").unwrap(), [APUNCT, ENDPAT]);
+        assert_eq!(compile(b":P").unwrap(), [APUNCT, ENDPAT]);
+        assert_eq!(compile(b": ").unwrap(), [PUNCT, ENDPAT]);
+        assert_eq!(compile(b":a:D:n").unwrap(), [ALPHA, DIGIT, NALPHA, ENDPAT],);
+        assert_eq!(error_msg(compile(b":x")), "Unknown : type");
+    }
+
+    #[test]
+    fn or() {
+        let cat = compiler(b"cat", CompileOptions::default()).unwrap();
+        let dog = compiler(b"d[o0]g", CompileOptions::default()).unwrap();
+        let either = cat.or(&dog).unwrap();
+        assert_eq!(either.pbuf, compile_extended(b"cat|d[o0]g").unwrap());
+        assert_eq!(either.decompile(), b"cat|d[o0]g");
+        let three = either.or(&cat).unwrap();
+        assert_eq!(three.pbuf, compile_extended(b"(cat|d[o0]g)|cat").unwrap());
+        assert_eq!(three.min_len(), 3);
+
+        let long = compiler(&[b'a'; 127], CompileOptions::default()).unwrap();
+        assert_eq!(long.or(&cat).unwrap_err().msg, "Pattern too complex");
+        let case_sensitive = CompileOptions {
+            case_insensitive: false,
+            ..CompileOptions::default()
+        };
+        let cat_sensitive = compiler(b"cat", case_sensitive).unwrap();
+        assert!(cat.or(&cat_sensitive).is_err());
+    }
+}
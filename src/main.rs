@@ -1,9 +1,13 @@
 use std::env::args_os;
+use std::process::exit;
 
 use decus_grep_rust::Compiler;
 
 fn main() {
-    let pat = args_os().skip(1).next().unwrap().into_encoded_bytes();
+    let pat = args_os().nth(1).unwrap().into_encoded_bytes();
     let mut compiler = Compiler::new(1);
-    compiler.compile(&pat).unwrap();
+    if let Err(err) = compiler.compile(&pat) {
+        eprintln!("{err}");
+        exit(1);
+    }
 }